@@ -0,0 +1,100 @@
+use async_compression::futures::bufread::ZstdEncoder;
+use futures::io::{AsyncRead, AsyncReadExt, BufReader};
+use ipfs::{Block, Cid, Error, Ipfs, IpfsTypes};
+use ipfs_unixfs::file::adder::FileAdder;
+use std::borrow::Borrow;
+
+/// Size of the chunks read out of the zstd encoder and handed to the `FileAdder` at a time. Equal
+/// to the default `FileAdder` chunk size (256 KiB), so a single read holds at most one block's
+/// worth of compressed input.
+const READ_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Reads `source` to completion, zstd-compressing it on the fly and chunking the compressed
+/// bytes into a UnixFS DAG via [`FileAdder`], storing every produced block in `ipfs`.
+///
+/// Returns the `Cid` of the root block once the whole source has been consumed. The result is
+/// the counterpart to [`crate::cat::cat`]: feeding the returned `Cid` back into `cat` and
+/// decompressing with `ZstdDecoder` reproduces `source` byte for byte.
+pub async fn add<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    source: impl AsyncRead + Unpin + Send + 'a,
+) -> Result<Cid, Error>
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
+    let mut encoder = ZstdEncoder::new(BufReader::new(source));
+    let mut adder = FileAdder::default();
+
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+
+    loop {
+        let read = encoder.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+
+        let mut fed = 0;
+        while fed < read {
+            let (blocks, consumed) = adder.push(&chunk[fed..read]);
+            for (cid, data) in blocks {
+                ipfs.borrow().put_block(Block { cid, data }).await?;
+            }
+            fed += consumed;
+        }
+    }
+
+    let mut root = None;
+    for (cid, data) in adder.finish() {
+        root = Some(cid);
+        ipfs.borrow().put_block(Block { cid, data }).await?;
+    }
+
+    root.ok_or_else(|| anyhow::format_err!("refusing to add an empty source: no root block produced"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cat::{CatOptions, cat};
+    use async_compression::futures::bufread::ZstdDecoder;
+    use futures::io::{AsyncReadExt, BufReader, Cursor};
+    use futures::stream::TryStreamExt;
+    use ipfs::unixfs::StartingPoint;
+    use ipfs::{IpfsOptions, TestTypes, UninitializedIpfs};
+
+    #[tokio::test]
+    async fn add_then_cat_round_trips_the_original_bytes() {
+        let mut opts = IpfsOptions::inmemory_with_generated_keys();
+        opts.mdns = false;
+        let (ipfs, fut): (Ipfs<TestTypes>, _) = UninitializedIpfs::new(opts).start().await.unwrap();
+        tokio::task::spawn(fut);
+
+        let original = b"the quick brown fox jumps over the lazy dog ".repeat(1000);
+
+        let cid = add(ipfs.clone(), Cursor::new(original.clone()))
+            .await
+            .unwrap();
+
+        let root = ipfs.get_block(&cid).await.unwrap();
+        let stream = cat(
+            ipfs.clone(),
+            StartingPoint::Right(root),
+            None,
+            CatOptions::default(),
+        )
+        .await
+        .unwrap();
+        let compressed = stream
+            .map_err(|_e| futures_io::Error::from_raw_os_error(5))
+            .into_async_read();
+
+        let mut decoded = Vec::new();
+        ZstdDecoder::new(BufReader::new(compressed))
+            .read_to_end(&mut decoded)
+            .await
+            .unwrap();
+
+        assert_eq!(decoded, original);
+    }
+}
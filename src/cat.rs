@@ -1,22 +1,121 @@
 use ipfs::{
-    Block, Ipfs, IpfsTypes, unixfs::{StartingPoint, TraversalFailed, ll::file::visit::IdleFileVisit},
+    Block, Cid, Ipfs, IpfsTypes, unixfs::{StartingPoint, TraversalFailed, ll::file::visit::IdleFileVisit},
 };
 use async_stream::stream;
-use futures::stream::Stream;
+use futures::stream::{FuturesOrdered, Stream, StreamExt};
 use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::ops::Range;
+use std::time::Duration;
+
+/// File-level metadata made available before the first byte of content has been streamed, so
+/// that front-ends (HTTP handlers, progress bars, ...) can size their output ahead of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Length, in bytes, of the content the accompanying stream will yield: the full file size,
+    /// or the clamped length of the requested range when one was given.
+    pub size: u64,
+}
+
+impl FileMetadata {
+    fn new(total_size: u64, range: Option<&Range<u64>>) -> Self {
+        let size = match range {
+            Some(range) => {
+                let start = range.start.min(total_size);
+                let end = range.end.min(total_size);
+                end.saturating_sub(start)
+            }
+            None => total_size,
+        };
+
+        FileMetadata { size }
+    }
+}
+
+/// Tuning knobs for a `cat` traversal over a possibly large, possibly flaky swarm.
+#[derive(Debug, Clone, Copy)]
+pub struct CatOptions {
+    /// Number of times a failed `get_block` is retried, with exponential backoff, before the
+    /// traversal gives up and yields a [`TraversalFailed::Loading`].
+    pub retries: u32,
+    /// Base delay before the first retry; doubled on every subsequent attempt.
+    pub backoff: Duration,
+    /// How many of the already-known upcoming block CIDs to fetch concurrently, instead of
+    /// waiting for each one in turn.
+    pub prefetch: usize,
+}
+
+impl Default for CatOptions {
+    fn default() -> Self {
+        CatOptions {
+            retries: 0,
+            backoff: Duration::from_millis(100),
+            prefetch: 1,
+        }
+    }
+}
+
+/// Fetches `cid` from `ipfs`, retrying up to `options.retries` times with exponential backoff
+/// before giving up. Shared by the file traversal below and by directory walking, so both pay
+/// the same resilience story against a flaky swarm.
+pub(crate) async fn get_block_with_retry<Types, MaybeOwned>(
+    ipfs: &MaybeOwned,
+    cid: &Cid,
+    options: &CatOptions,
+) -> Result<Block, ipfs::Error>
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>>,
+{
+    let mut attempt = 0;
+    loop {
+        match ipfs.borrow().get_block(cid).await {
+            Ok(block) => return Ok(block),
+            Err(e) if attempt < options.retries => {
+                let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+                tracing::debug!(%cid, attempt, error = %e, "block fetch failed, retrying");
+                tokio::time::sleep(options.backoff * factor).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
 pub async fn cat<'a, Types, MaybeOwned>(
     ipfs: MaybeOwned,
     starting_point: impl Into<StartingPoint>,
     range: Option<Range<u64>>,
+    options: CatOptions,
 ) -> Result<impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a, TraversalFailed>
 where
     Types: IpfsTypes,
-    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+    MaybeOwned: Borrow<Ipfs<Types>> + Clone + Send + 'a,
+{
+    let (_metadata, stream) = cat_with_metadata(ipfs, starting_point, range, options).await?;
+    Ok(stream)
+}
+
+/// Same as [`cat`], but also returns the [`FileMetadata`] resolved from the root block before
+/// any content is streamed, so that e.g. an HTTP front-end can set `Content-Length` up front.
+pub async fn cat_with_metadata<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    starting_point: impl Into<StartingPoint>,
+    range: Option<Range<u64>>,
+    options: CatOptions,
+) -> Result<
+    (
+        FileMetadata,
+        impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a,
+    ),
+    TraversalFailed,
+>
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Clone + Send + 'a,
 {
     let mut visit = IdleFileVisit::default();
-    if let Some(range) = range {
+    if let Some(range) = range.clone() {
         visit = visit.with_target_range(range);
     }
 
@@ -40,27 +139,24 @@ where
     let mut cache = None;
     // Start the visit from the root block. We need to move the both components as Options into the
     // stream as we can't yet return them from this Future context.
-    let (visit, bytes) = match visit.start(&data) {
-        Ok((bytes, _, _, visit)) => {
+    let (visit, bytes, metadata) = match visit.start(&data) {
+        Ok((bytes, file_size, _, visit)) => {
             let bytes = if !bytes.is_empty() {
                 Some(bytes.to_vec())
             } else {
                 None
             };
 
-            (visit, bytes)
+            (visit, bytes, FileMetadata::new(file_size, range.as_ref()))
         }
         Err(e) => {
             return Err(TraversalFailed::Walking(cid, e));
         }
     };
 
-    // FIXME: we could use the above file_size to set the content-length ... but calculating it
-    // with the ranges is not ... trivial?
-
     // using async_stream here at least to get on faster; writing custom streams is not too easy
     // but this might be easy enough to write open.
-    Ok(stream! {
+    let stream = stream! {
 
         if let Some(bytes) = bytes {
             yield Ok(bytes);
@@ -71,21 +167,59 @@ where
             None => return,
         };
 
+        // Blocks whose fetch was kicked off ahead of time because they were already known from
+        // `pending_links`, keyed by Cid so `continue_walk` can consume them out of the arrival
+        // order the prefetch queue resolves them in.
+        let mut prefetched: HashMap<Cid, Vec<u8>> = HashMap::new();
+        // CIDs that already have a fetch in `in_flight`, so the same block is never requested
+        // twice concurrently.
+        let mut scheduled: HashSet<Cid> = HashSet::new();
+        let mut in_flight = FuturesOrdered::new();
+
+        // The block we actually need next must be fetched concurrently with its prefetched
+        // siblings, not after them, or "prefetch" just adds latency instead of hiding it.
+        //
+        // Top up the prefetch queue with CIDs this level of the walk already knows about, too,
+        // so a slow peer for one block doesn't stall the whole download waiting on a single
+        // round-trip at a time.
         loop {
-            // TODO: if it was possible, it would make sense to start downloading N of these
-            // we could just create an FuturesUnordered which would drop the value right away. that
-            // would probably always cost many unnecessary clones, but it would be nice to "shut"
-            // the subscriber so that it will only resolve to a value but still keep the operation
-            // going. Not that we have any "operation" concept of the Want yet.
-            let (next, _) = visit.pending_links();
+            let (next, rest) = visit.pending_links();
+            let next = next.to_owned();
 
-            let borrow = ipfs.borrow();
-            let Block { cid, data } = match borrow.get_block(next).await {
-                Ok(block) => block,
-                Err(e) => {
-                    yield Err(TraversalFailed::Loading(next.to_owned(), e));
-                    return;
-                },
+            let to_schedule = std::iter::once(next.clone())
+                .filter(|cid| !prefetched.contains_key(cid))
+                .chain(rest.take(options.prefetch.saturating_sub(1)).map(Cid::to_owned))
+                .filter(|cid| scheduled.insert(cid.clone()))
+                .collect::<Vec<_>>();
+
+            for cid in to_schedule {
+                let ipfs = ipfs.clone();
+                let options = options;
+                in_flight.push_back(async move {
+                    let result = get_block_with_retry(&ipfs, &cid, &options).await;
+                    (cid, result)
+                });
+            }
+
+            let data = loop {
+                if let Some(data) = prefetched.remove(&next) {
+                    break data;
+                }
+
+                match in_flight.next().await {
+                    Some((cid, Ok(Block { data, .. }))) => {
+                        scheduled.remove(&cid);
+                        if cid == next {
+                            break data;
+                        }
+                        prefetched.insert(cid, data);
+                    }
+                    Some((cid, Err(e))) => {
+                        yield Err(TraversalFailed::Loading(cid, e));
+                        return;
+                    }
+                    None => unreachable!("next is always scheduled before we wait on it"),
+                }
             };
 
             match visit.continue_walk(&data, &mut cache) {
@@ -101,10 +235,32 @@ where
                     }
                 }
                 Err(e) => {
-                    yield Err(TraversalFailed::Walking(cid, e));
+                    yield Err(TraversalFailed::Walking(next, e));
                     return;
                 }
             }
         }
-    })
-}
\ No newline at end of file
+    };
+
+    Ok((metadata, stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileMetadata;
+
+    #[test]
+    fn file_metadata_with_no_range_is_the_whole_file() {
+        assert_eq!(FileMetadata::new(1024, None).size, 1024);
+    }
+
+    #[test]
+    fn file_metadata_clamps_range_to_total_size() {
+        assert_eq!(FileMetadata::new(100, Some(&(50..1000))).size, 50);
+    }
+
+    #[test]
+    fn file_metadata_handles_a_reversed_range_without_panicking() {
+        assert_eq!(FileMetadata::new(100, Some(&(80..10))).size, 0);
+    }
+}
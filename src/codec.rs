@@ -0,0 +1,117 @@
+use async_compression::futures::bufread::{GzipDecoder, XzDecoder, ZstdDecoder};
+use futures::io::{AsyncRead, AsyncReadExt, BufReader, Chain, Cursor};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+// Only the first four bytes of the five-byte xz magic (`FD 37 7A 58 5A`) are needed: they are
+// already unique among the codecs we detect.
+const XZ_MAGIC_PREFIX: [u8; 4] = [0xFD, 0x37, 0x7A, 0x58];
+
+type Prefixed<R> = BufReader<Chain<Cursor<Vec<u8>>, R>>;
+
+/// A reader whose compression codec was sniffed from its first bytes rather than assumed by the
+/// caller, so that `cat`'s output can be consumed regardless of what it happens to be compressed
+/// with. Reads transparently as an [`AsyncRead`], regardless of which codec was detected.
+pub enum DetectedDecoder<R> {
+    Zstd(ZstdDecoder<Prefixed<R>>),
+    Gzip(GzipDecoder<Prefixed<R>>),
+    Xz(XzDecoder<Prefixed<R>>),
+    Plain(Prefixed<R>),
+}
+
+impl<R> AsyncRead for DetectedDecoder<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            DetectedDecoder::Zstd(r) => Pin::new(r).poll_read(cx, buf),
+            DetectedDecoder::Gzip(r) => Pin::new(r).poll_read(cx, buf),
+            DetectedDecoder::Xz(r) => Pin::new(r).poll_read(cx, buf),
+            DetectedDecoder::Plain(r) => Pin::new(r).poll_read(cx, buf),
+        }
+    }
+}
+
+/// Peeks the first four bytes of `reader` and wraps it in the matching `async_compression`
+/// decoder: zstd, gzip or xz by magic number, falling back to passing the bytes through
+/// unmodified when none match. The peeked prefix is re-emitted ahead of the rest of `reader`, so
+/// no bytes are lost to the sniff.
+pub async fn detect<R>(mut reader: R) -> io::Result<DetectedDecoder<R>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut prefix = [0u8; 4];
+    let mut filled = 0;
+    while filled < prefix.len() {
+        let n = reader.read(&mut prefix[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+
+    // Replay only the bytes we actually read: padding a short source out to 4 zero bytes would
+    // corrupt small files (or anything that hit EOF before filling the peek buffer).
+    let prefixed = BufReader::new(Cursor::new(prefix[..filled].to_vec()).chain(reader));
+
+    Ok(if filled == 4 && prefix == ZSTD_MAGIC {
+        DetectedDecoder::Zstd(ZstdDecoder::new(prefixed))
+    } else if filled >= 2 && prefix[..2] == GZIP_MAGIC {
+        DetectedDecoder::Gzip(GzipDecoder::new(prefixed))
+    } else if filled == 4 && prefix == XZ_MAGIC_PREFIX {
+        DetectedDecoder::Xz(XzDecoder::new(prefixed))
+    } else {
+        DetectedDecoder::Plain(prefixed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+
+    async fn read_all(mut reader: impl AsyncRead + Unpin) -> Vec<u8> {
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn detects_plain_passthrough() {
+        let decoder = detect(Cursor::new(b"hello, world".to_vec())).await.unwrap();
+        assert!(matches!(decoder, DetectedDecoder::Plain(_)));
+        assert_eq!(read_all(decoder).await, b"hello, world");
+    }
+
+    #[tokio::test]
+    async fn detects_gzip_magic() {
+        let mut source = GZIP_MAGIC.to_vec();
+        source.extend_from_slice(&[0u8; 16]);
+        let decoder = detect(Cursor::new(source)).await.unwrap();
+        assert!(matches!(decoder, DetectedDecoder::Gzip(_)));
+    }
+
+    #[tokio::test]
+    async fn short_source_is_not_zero_padded() {
+        // A source shorter than the 4-byte peek buffer must come back byte-for-byte, not
+        // padded out with the zeroes that used to fill the rest of the peek buffer.
+        let decoder = detect(Cursor::new(b"hi".to_vec())).await.unwrap();
+        assert!(matches!(decoder, DetectedDecoder::Plain(_)));
+        assert_eq!(read_all(decoder).await, b"hi");
+    }
+
+    #[tokio::test]
+    async fn empty_source_is_passthrough() {
+        let decoder = detect(Cursor::new(Vec::new())).await.unwrap();
+        assert!(matches!(decoder, DetectedDecoder::Plain(_)));
+        assert_eq!(read_all(decoder).await, Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod add;
+pub mod cat;
+pub mod codec;
+pub mod dir;
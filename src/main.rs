@@ -1,13 +1,14 @@
-mod cat;
+use casper_archiver::{add, cat, codec, dir};
 
 use futures::AsyncReadExt;
 use futures::TryStreamExt;
 use futures::pin_mut;
+use futures::stream::StreamExt;
 use ipfs::{Error, Ipfs, IpfsOptions, IpfsPath, MultiaddrWithPeerId, TestTypes, UninitializedIpfs};
 use std::env;
 use std::process::exit;
 use tokio::io::AsyncWriteExt;
-use async_compression::futures::bufread::ZstdDecoder;
+use tokio_util::compat::TokioAsyncReadCompatExt;
 
 use tracing::Span;
 use tracing_futures::Instrument;
@@ -16,27 +17,26 @@ use tracing_futures::Instrument;
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let (bootstrappers, path, target) = match parse_options() {
+    let (bootstrappers, mode, target) = match parse_options() {
         Ok(Some(tuple)) => tuple,
         Ok(None) => {
             eprintln!(
-                "Usage: fetch_and_cat [--default-bootstrappers] <IPFS_PATH | CID> [MULTIADDR]"
+                "Usage: fetch_and_cat [--default-bootstrappers] [--dir] <add | IPFS_PATH | CID> [MULTIADDR]"
             );
             eprintln!();
             eprintln!(
                 "Example will try to find the file by the given IPFS_PATH and print its contents to stdout."
             );
             eprintln!();
-            eprintln!("The example has three modes in the order of precedence:");
+            eprintln!("The example has three modes of operation:");
+            eprintln!("1. `add` reads stdin, stores it, and prints the resulting root CID");
+            eprintln!("2. `<IPFS_PATH | CID>` fetches and prints the contents of a single file");
             eprintln!(
-                "1. When --default-bootstrappers is given, use default bootstrappers to find the content"
-            );
-            eprintln!(
-                "2. When IPFS_PATH and MULTIADDR are given, connect to MULTIADDR to get the file"
-            );
-            eprintln!(
-                "3. When only IPFS_PATH is given, wait to be connected to by another ipfs node"
+                "3. `--dir <IPFS_PATH | CID>` recursively walks a directory, printing each entry's path and size"
             );
+            eprintln!();
+            eprintln!("--default-bootstrappers uses the default bootstrappers to find the content;");
+            eprintln!("otherwise, pass a MULTIADDR to connect to directly, or wait to be connected to.");
             exit(0);
         }
         Err(e) => {
@@ -70,7 +70,7 @@ async fn main() {
         println!("Done bootstrapping.");
     } else if let Some(target) = target {
         ipfs.connect(target).await.unwrap();
-    } else {
+    } else if let Mode::Cat(path) | Mode::CatDir(path) = &mode {
         let (_, addresses) = ipfs.identity().await.unwrap();
         assert!(!addresses.is_empty(), "Zero listening addresses");
 
@@ -83,54 +83,115 @@ async fn main() {
         eprintln!();
     }
 
-    // Calling Ipfs::cat_unixfs returns a future of a stream, because the path resolving
-    // and the initial block loading will require at least one async call before any actual file
-    // content can be *streamed*.
-    let stream = cat::cat(ipfs, path, None);
-    let stream = stream.instrument(Span::current()).await.unwrap();
-    let stream = stream.map_err(|_e| futures_io::Error::from_raw_os_error(5));
-
-    pin_mut!(stream);
-
-    let async_reader = stream.into_async_read();
-    let mut zstd_reader = ZstdDecoder::new(async_reader);
-
-    let mut stdout = tokio::io::stdout();
-    let mut buf = [0u8; 1024];
-    let mut bytes_read = 0usize;
-    let mut bytes: Vec<u8> = Vec::with_capacity(1024);
-
-    loop {
-        // This could be made more performant by polling the stream while writing to stdout.
-        // use futures::stream::StreamExt;
-        // match stream.next().await {
-        //     Some(Ok(bytes)) => {
-        //         stdout.write_all(&bytes).await.unwrap();
-        //     }
-        //     Some(Err(e)) => {
-        //         eprintln!("Error: {}", e);
-        //         exit(1);
-        //     }
-        //     None => break,
-        // }
-        match zstd_reader.read(&mut buf).await {
-            Ok(len) => {
-                if len == 0 {
-                    stdout.write_all(format!("Got {} bytes, stream ended\n", len).as_bytes()).await.unwrap();
-                    break;
+    match mode {
+        Mode::Add => {
+            // Bridge tokio's stdin into the futures `AsyncRead` that `add` wants, so large
+            // sources are streamed through rather than buffered into memory up front.
+            let stdin = tokio::io::stdin().compat();
+
+            let cid = add::add(ipfs, stdin).await.unwrap();
+            println!("{}", cid);
+        }
+        Mode::CatDir(path) => {
+            let stream = dir::cat_dir(ipfs, path, cat::CatOptions::default())
+                .instrument(Span::current())
+                .await
+                .unwrap();
+            pin_mut!(stream);
+
+            while let Some(entry) = stream.next().await {
+                match entry {
+                    Ok((relative_path, mut content)) => {
+                        let mut total = 0usize;
+                        while let Some(chunk) = content.next().await {
+                            match chunk {
+                                Ok(bytes) => total += bytes.len(),
+                                Err(e) => {
+                                    eprintln!("Error reading {}: {:?}", relative_path.display(), e);
+                                    break;
+                                }
+                            }
+                        }
+                        println!("{}\t{} bytes", relative_path.display(), total);
+                    }
+                    Err(e) => eprintln!("Error: {:?}", e),
                 }
-                stdout.write_all(format!("Got {} bytes\n", len).as_bytes()).await.unwrap();
-                bytes_read += len;
-                bytes.extend_from_slice(&buf[..len]);
-            },
-            Err(error) => {
-                stdout.write_all(format!("Got error {}\n", error).as_bytes()).await.unwrap();
             }
         }
+        Mode::Cat(path) => {
+            // Calling Ipfs::cat_unixfs returns a future of a stream, because the path resolving
+            // and the initial block loading will require at least one async call before any actual file
+            // content can be *streamed*.
+            let result = cat::cat_with_metadata(ipfs, path, None, cat::CatOptions::default());
+            let (metadata, stream) = result.instrument(Span::current()).await.unwrap();
+            let stream = stream.map_err(|_e| futures_io::Error::from_raw_os_error(5));
+
+            pin_mut!(stream);
+
+            let async_reader = stream.into_async_read();
+            let mut decoded_reader = codec::detect(async_reader).await.unwrap();
+
+            let mut stdout = tokio::io::stdout();
+            let mut buf = [0u8; 1024];
+            let mut bytes_read = 0usize;
+            let mut bytes: Vec<u8> = Vec::with_capacity(1024);
+
+            stdout
+                .write_all(format!("Fetching {} bytes\n", metadata.size).as_bytes())
+                .await
+                .unwrap();
+
+            loop {
+                // This could be made more performant by polling the stream while writing to stdout.
+                // use futures::stream::StreamExt;
+                // match stream.next().await {
+                //     Some(Ok(bytes)) => {
+                //         stdout.write_all(&bytes).await.unwrap();
+                //     }
+                //     Some(Err(e)) => {
+                //         eprintln!("Error: {}", e);
+                //         exit(1);
+                //     }
+                //     None => break,
+                // }
+                match decoded_reader.read(&mut buf).await {
+                    Ok(len) => {
+                        if len == 0 {
+                            stdout
+                                .write_all(format!("Got {} bytes, stream ended\n", len).as_bytes())
+                                .await
+                                .unwrap();
+                            break;
+                        }
+                        bytes_read += len;
+                        stdout
+                            .write_all(
+                                format!("Got {} bytes ({}/{})\n", len, bytes_read, metadata.size)
+                                    .as_bytes(),
+                            )
+                            .await
+                            .unwrap();
+                        bytes.extend_from_slice(&buf[..len]);
+                    }
+                    Err(error) => {
+                        stdout
+                            .write_all(format!("Got error {}\n", error).as_bytes())
+                            .await
+                            .unwrap();
+                    }
+                }
+            }
+            stdout
+                .write_all(format!("{} total decoded bytes read\n", bytes_read).as_bytes())
+                .await
+                .unwrap();
+            let as_string = String::from_utf8(bytes).expect("Couldn't parse bytes.");
+            stdout
+                .write_all(format!("IPFS file contents:\n{}", as_string).as_bytes())
+                .await
+                .unwrap();
+        }
     }
-    stdout.write_all(format!("{} total decoded bytes read\n", bytes_read).as_bytes()).await.unwrap();
-    let as_string = String::from_utf8(bytes).expect("Couldn't parse bytes.");
-    stdout.write_all(format!("IPFS file contents:\n{}", as_string).as_bytes()).await.unwrap();
 }
 
 #[derive(PartialEq)]
@@ -139,12 +200,23 @@ enum BootstrapperOption {
     ConnectionsOnly,
 }
 
-fn parse_options(
-) -> Result<Option<(BootstrapperOption, IpfsPath, Option<MultiaddrWithPeerId>)>, Error> {
+/// What this invocation should do once connected to the swarm.
+enum Mode {
+    /// Store stdin into IPFS and print the resulting root CID.
+    Add,
+    /// Fetch and print the contents of a single file.
+    Cat(IpfsPath),
+    /// Recursively walk a directory, printing each entry's path and size.
+    CatDir(IpfsPath),
+}
+
+fn parse_options() -> Result<Option<(BootstrapperOption, Mode, Option<MultiaddrWithPeerId>)>, Error>
+{
     let mut args = env::args().skip(1).peekable();
 
     // by default use only the manual connections
     let mut bootstrappers = BootstrapperOption::ConnectionsOnly;
+    let mut dir_mode = false;
 
     while let Some(option) = args.peek() {
         if !option.starts_with("--") {
@@ -155,18 +227,29 @@ fn parse_options(
 
         if option == "--default-bootstrappers" {
             bootstrappers = BootstrapperOption::RestoreDefault;
+        } else if option == "--dir" {
+            dir_mode = true;
         } else {
             return Err(anyhow::format_err!("unknown option: {}", option));
         }
     }
 
-    let path = if let Some(path) = args.next() {
-        path.parse::<IpfsPath>()
-            .map_err(|e| e.context(format!("failed to parse {:?} as IpfsPath", path)))?
+    let first = if let Some(first) = args.next() {
+        first
     } else {
         return Ok(None);
     };
 
+    let mode = if !dir_mode && first == "add" {
+        Mode::Add
+    } else {
+        let path = first
+            .parse::<IpfsPath>()
+            .map_err(|e| e.context(format!("failed to parse {:?} as IpfsPath", first)))?;
+
+        if dir_mode { Mode::CatDir(path) } else { Mode::Cat(path) }
+    };
+
     let target = if let Some(multiaddr) = args.next() {
         let ma = multiaddr.parse::<MultiaddrWithPeerId>().map_err(|e| {
             Error::new(e).context(format!(
@@ -179,5 +262,5 @@ fn parse_options(
         None
     };
 
-    Ok(Some((bootstrappers, path, target)))
+    Ok(Some((bootstrappers, mode, target)))
 }